@@ -3,9 +3,9 @@ use pyo3::prelude::*;
 use pythonize::pythonize;
 use std::sync::Arc;
 use taplock_rs::{
-    auth::{entra_id, google, keycloak},
-    OAuth2Client, OAuth2Response, TapLockError, ACCESS_TOKEN_COOKIE_NAME,
-    REFRESH_TOKEN_COOKIE_NAME, TAPLOCK_CALLBACK_ENDPOINT,
+    auth::{entra_id, google, keycloak, oidc},
+    DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier, TapLockError,
+    TokenTypeHint, ACCESS_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, TAPLOCK_CALLBACK_ENDPOINT,
 };
 
 #[derive(Clone, Debug)]
@@ -13,6 +13,7 @@ enum ClientEnum {
     Google(google::GoogleOAuth2Client),
     EntraId(entra_id::AzureADOAuth2Client),
     Keycloak(keycloak::KeycloakOAuth2Client),
+    Oidc(oidc::GenericOidcClient),
 }
 
 #[async_trait::async_trait]
@@ -25,16 +26,20 @@ impl OAuth2Client for ClientEnum {
             ClientEnum::Google(c) => c.exchange_refresh_token(refresh_token).await,
             ClientEnum::EntraId(c) => c.exchange_refresh_token(refresh_token).await,
             ClientEnum::Keycloak(c) => c.exchange_refresh_token(refresh_token).await,
+            ClientEnum::Oidc(c) => c.exchange_refresh_token(refresh_token).await,
         }
     }
     async fn exchange_code(
         &self,
         code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
     ) -> std::result::Result<OAuth2Response, TapLockError> {
         match self {
-            ClientEnum::Google(c) => c.exchange_code(code).await,
-            ClientEnum::EntraId(c) => c.exchange_code(code).await,
-            ClientEnum::Keycloak(c) => c.exchange_code(code).await,
+            ClientEnum::Google(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::EntraId(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::Keycloak(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::Oidc(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
         }
     }
     fn decode_access_token(
@@ -45,13 +50,56 @@ impl OAuth2Client for ClientEnum {
             ClientEnum::Google(c) => c.decode_access_token(access_token),
             ClientEnum::EntraId(c) => c.decode_access_token(access_token),
             ClientEnum::Keycloak(c) => c.decode_access_token(access_token),
+            ClientEnum::Oidc(c) => c.decode_access_token(access_token),
         }
     }
-    fn get_authorization_url(&self) -> String {
+    fn get_authorization_url(&self) -> (String, String, String, String) {
         match self {
             ClientEnum::Google(c) => c.get_authorization_url(),
             ClientEnum::EntraId(c) => c.get_authorization_url(),
             ClientEnum::Keycloak(c) => c.get_authorization_url(),
+            ClientEnum::Oidc(c) => c.get_authorization_url(),
+        }
+    }
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> std::result::Result<(), TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::EntraId(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::Keycloak(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::Oidc(c) => c.revoke_token(token, token_type_hint).await,
+        }
+    }
+    fn end_session_url(&self) -> String {
+        match self {
+            ClientEnum::Google(c) => c.end_session_url(),
+            ClientEnum::EntraId(c) => c.end_session_url(),
+            ClientEnum::Keycloak(c) => c.end_session_url(),
+            ClientEnum::Oidc(c) => c.end_session_url(),
+        }
+    }
+    async fn request_device_code(
+        &self,
+    ) -> std::result::Result<DeviceAuthorizationResponse, TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.request_device_code().await,
+            ClientEnum::EntraId(c) => c.request_device_code().await,
+            ClientEnum::Keycloak(c) => c.request_device_code().await,
+            ClientEnum::Oidc(c) => c.request_device_code().await,
+        }
+    }
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.poll_device_token(device_code).await,
+            ClientEnum::EntraId(c) => c.poll_device_token(device_code).await,
+            ClientEnum::Keycloak(c) => c.poll_device_token(device_code).await,
+            ClientEnum::Oidc(c) => c.poll_device_token(device_code).await,
         }
     }
 }
@@ -65,12 +113,34 @@ struct TapLockClient {
 
 #[pymethods]
 impl TapLockClient {
-    #[pyo3(text_signature = "($self, code)")]
-    fn exchange_code<'p>(&self, py: Python<'p>, code: String) -> PyResult<Bound<'p, PyAny>> {
+    // `code_verifier` must be the PKCE verifier returned by
+    // `get_authorization_url` for this handshake; pass `None` only with
+    // `skip_pkce=True`, for a confidential-client exchange that deliberately
+    // skips PKCE. Otherwise omitting it raises instead of silently
+    // proceeding without PKCE.
+    #[pyo3(signature = (code, code_verifier, expected_nonce, skip_pkce = false))]
+    #[pyo3(text_signature = "($self, code, code_verifier, expected_nonce, skip_pkce=False)")]
+    fn exchange_code<'p>(
+        &self,
+        py: Python<'p>,
+        code: String,
+        code_verifier: Option<String>,
+        expected_nonce: Option<String>,
+        skip_pkce: bool,
+    ) -> PyResult<Bound<'p, PyAny>> {
+        let pkce_verifier = match (code_verifier, skip_pkce) {
+            (Some(verifier), _) => PkceVerifier::Verifier(verifier),
+            (None, true) => PkceVerifier::SkipPkce,
+            (None, false) => {
+                return Err(PyValueError::new_err(
+                    "code_verifier is required unless skip_pkce=True",
+                ))
+            }
+        };
         let client = self.client.clone();
         pyo3_async_runtimes::tokio::future_into_py(py, async move {
             let response = client
-                .exchange_code(code)
+                .exchange_code(code, pkce_verifier, expected_nonce)
                 .await
                 .map_err(|e| PyValueError::new_err(e.to_string()))?;
 
@@ -116,9 +186,72 @@ impl TapLockClient {
         pythonize(py, &response).map_err(|e| PyValueError::new_err(e.to_string()))
     }
 
-    fn get_authorization_url(&self) -> String {
+    fn get_authorization_url(&self) -> (String, String, String, String) {
         self.client.get_authorization_url()
     }
+
+    #[pyo3(text_signature = "($self, token, is_refresh_token)")]
+    fn revoke_token<'p>(
+        &self,
+        py: Python<'p>,
+        token: String,
+        is_refresh_token: bool,
+    ) -> PyResult<Bound<'p, PyAny>> {
+        let client = self.client.clone();
+        let token_type_hint = if is_refresh_token {
+            TokenTypeHint::RefreshToken
+        } else {
+            TokenTypeHint::AccessToken
+        };
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            client
+                .revoke_token(token, token_type_hint)
+                .await
+                .map_err(|e| PyValueError::new_err(e.to_string()))
+        })
+    }
+
+    fn end_session_url(&self) -> String {
+        self.client.end_session_url()
+    }
+
+    #[pyo3(text_signature = "($self)")]
+    fn request_device_code<'p>(&self, py: Python<'p>) -> PyResult<Bound<'p, PyAny>> {
+        let client = self.client.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let response = client
+                .request_device_code()
+                .await
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+            Python::attach(|py| {
+                let bound =
+                    pythonize(py, &response).map_err(|e| PyValueError::new_err(e.to_string()))?;
+                Ok(bound.unbind())
+            })
+        })
+    }
+
+    #[pyo3(text_signature = "($self, device_code)")]
+    fn poll_device_token<'p>(
+        &self,
+        py: Python<'p>,
+        device_code: String,
+    ) -> PyResult<Bound<'p, PyAny>> {
+        let client = self.client.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let response = client
+                .poll_device_token(device_code)
+                .await
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+            Python::attach(|py| {
+                let bound =
+                    pythonize(py, &response).map_err(|e| PyValueError::new_err(e.to_string()))?;
+                Ok(bound.unbind())
+            })
+        })
+    }
 }
 
 #[pyfunction]
@@ -211,6 +344,36 @@ fn initialize_keycloak<'p>(
     })
 }
 
+#[pyfunction]
+fn initialize_oidc<'p>(
+    py: Python<'p>,
+    client_id: String,
+    client_secret: String,
+    issuer_url: String,
+    app_url: String,
+    use_refresh_token: bool,
+) -> PyResult<Bound<'p, PyAny>> {
+    let app_url_clone = app_url.clone();
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        let client = oidc::build_oauth2_state_oidc(
+            &client_id,
+            &client_secret,
+            &issuer_url,
+            &app_url_clone,
+            use_refresh_token,
+        )
+        .await
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let taplock_client = TapLockClient {
+            client: Arc::new(ClientEnum::Oidc(client)),
+            app_url: app_url_clone,
+        };
+
+        Ok(taplock_client)
+    })
+}
+
 #[pyfunction]
 fn initialize_google_from_env<'p>(py: Python<'p>) -> PyResult<Bound<'p, PyAny>> {
     pyo3_async_runtimes::tokio::future_into_py(py, async move {
@@ -265,6 +428,24 @@ fn initialize_keycloak_from_env<'p>(py: Python<'p>) -> PyResult<Bound<'p, PyAny>
     })
 }
 
+#[pyfunction]
+fn initialize_oidc_from_env<'p>(py: Python<'p>) -> PyResult<Bound<'p, PyAny>> {
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        let client = oidc::GenericOidcClient::from_env()
+            .await
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let app_url = std::env::var("TAPLOCK_APP_URL").unwrap_or_default();
+
+        let taplock_client = TapLockClient {
+            client: Arc::new(ClientEnum::Oidc(client)),
+            app_url,
+        };
+
+        Ok(taplock_client)
+    })
+}
+
 #[pyfunction]
 fn get_access_token_cookie_name() -> &'static str {
     ACCESS_TOKEN_COOKIE_NAME
@@ -302,5 +483,9 @@ mod taplock {
     #[pymodule_export]
     use super::initialize_keycloak_from_env;
     #[pymodule_export]
+    use super::initialize_oidc;
+    #[pymodule_export]
+    use super::initialize_oidc_from_env;
+    #[pymodule_export]
     use super::TapLockClient;
 }
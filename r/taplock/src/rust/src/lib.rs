@@ -3,9 +3,11 @@ use std::sync::Arc;
 use tokio::sync::oneshot::{self, error::TryRecvError};
 
 use taplock_rs::{
-    auth::{entra_id, google},
-    keycloak, OAuth2Client, OAuth2Response, TapLockError, ACCESS_TOKEN_COOKIE_NAME,
-    REFRESH_TOKEN_COOKIE_NAME, TAPLOCK_CALLBACK_ENDPOINT,
+    auth::{entra_id, google, oidc},
+    extendr::device_poll_error_into_robj,
+    keycloak, DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier,
+    TapLockError, TokenTypeHint, ACCESS_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME,
+    TAPLOCK_CALLBACK_ENDPOINT,
 };
 
 #[derive(Clone, Debug)]
@@ -13,6 +15,7 @@ enum ClientEnum {
     Google(google::GoogleOAuth2Client),
     EntraId(entra_id::AzureADOAuth2Client),
     Keycloak(keycloak::KeycloakOAuth2Client),
+    Oidc(oidc::GenericOidcClient),
 }
 
 #[async_trait::async_trait]
@@ -25,16 +28,20 @@ impl OAuth2Client for ClientEnum {
             ClientEnum::Google(c) => c.exchange_refresh_token(refresh_token).await,
             ClientEnum::EntraId(c) => c.exchange_refresh_token(refresh_token).await,
             ClientEnum::Keycloak(c) => c.exchange_refresh_token(refresh_token).await,
+            ClientEnum::Oidc(c) => c.exchange_refresh_token(refresh_token).await,
         }
     }
     async fn exchange_code(
         &self,
         code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
     ) -> std::result::Result<OAuth2Response, TapLockError> {
         match self {
-            ClientEnum::Google(c) => c.exchange_code(code).await,
-            ClientEnum::EntraId(c) => c.exchange_code(code).await,
-            ClientEnum::Keycloak(c) => c.exchange_code(code).await,
+            ClientEnum::Google(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::EntraId(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::Keycloak(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
+            ClientEnum::Oidc(c) => c.exchange_code(code, code_verifier, expected_nonce).await,
         }
     }
     fn decode_access_token(
@@ -45,13 +52,56 @@ impl OAuth2Client for ClientEnum {
             ClientEnum::Google(c) => c.decode_access_token(access_token),
             ClientEnum::EntraId(c) => c.decode_access_token(access_token),
             ClientEnum::Keycloak(c) => c.decode_access_token(access_token),
+            ClientEnum::Oidc(c) => c.decode_access_token(access_token),
         }
     }
-    fn get_authorization_url(&self) -> String {
+    fn get_authorization_url(&self) -> (String, String, String, String) {
         match self {
             ClientEnum::Google(c) => c.get_authorization_url(),
             ClientEnum::EntraId(c) => c.get_authorization_url(),
             ClientEnum::Keycloak(c) => c.get_authorization_url(),
+            ClientEnum::Oidc(c) => c.get_authorization_url(),
+        }
+    }
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> std::result::Result<(), TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::EntraId(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::Keycloak(c) => c.revoke_token(token, token_type_hint).await,
+            ClientEnum::Oidc(c) => c.revoke_token(token, token_type_hint).await,
+        }
+    }
+    fn end_session_url(&self) -> String {
+        match self {
+            ClientEnum::Google(c) => c.end_session_url(),
+            ClientEnum::EntraId(c) => c.end_session_url(),
+            ClientEnum::Keycloak(c) => c.end_session_url(),
+            ClientEnum::Oidc(c) => c.end_session_url(),
+        }
+    }
+    async fn request_device_code(
+        &self,
+    ) -> std::result::Result<DeviceAuthorizationResponse, TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.request_device_code().await,
+            ClientEnum::EntraId(c) => c.request_device_code().await,
+            ClientEnum::Keycloak(c) => c.request_device_code().await,
+            ClientEnum::Oidc(c) => c.request_device_code().await,
+        }
+    }
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        match self {
+            ClientEnum::Google(c) => c.poll_device_token(device_code).await,
+            ClientEnum::EntraId(c) => c.poll_device_token(device_code).await,
+            ClientEnum::Keycloak(c) => c.poll_device_token(device_code).await,
+            ClientEnum::Oidc(c) => c.poll_device_token(device_code).await,
         }
     }
 }
@@ -87,6 +137,22 @@ fn parse_cookies(cookie_string: Option<&str>) -> List {
     List::from_pairs(cookies)
 }
 
+// Encrypts `value` under `TAPLOCK_COOKIE_SECRET`, scoped to `name`. R
+// middleware should call this before setting the refresh token, CSRF state,
+// PKCE verifier, or nonce cookies, instead of storing them in plaintext.
+#[extendr]
+fn seal_cookie(name: String, value: String) -> Result<String> {
+    taplock_rs::auth::seal_cookie(&name, &value).map_err(Into::into)
+}
+
+// Decrypts a value produced by `seal_cookie`. Errors (rather than returning
+// garbage) if `sealed` was tampered with, sealed under a different key, or
+// sealed under a different `name`.
+#[extendr]
+fn open_cookie(name: String, sealed: String) -> Result<String> {
+    taplock_rs::auth::open_cookie(&name, &sealed).map_err(Into::into)
+}
+
 #[extendr]
 enum FutureResult {
     Error(Robj),
@@ -140,6 +206,40 @@ impl AsyncFuture {
     }
 }
 
+#[extendr]
+struct AsyncUnitFuture {
+    rx: oneshot::Receiver<std::result::Result<(), TapLockError>>,
+}
+
+#[extendr]
+impl AsyncUnitFuture {
+    fn poll(&mut self) -> FutureResult {
+        match self.rx.try_recv() {
+            Ok(Ok(())) => FutureResult::Ready(NULL.into_robj()),
+            Ok(Err(err)) => FutureResult::Error(err.into_robj()),
+            Err(TryRecvError::Empty) => FutureResult::Pending,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+#[extendr]
+struct AsyncDeviceCodeFuture {
+    rx: oneshot::Receiver<std::result::Result<DeviceAuthorizationResponse, TapLockError>>,
+}
+
+#[extendr]
+impl AsyncDeviceCodeFuture {
+    fn poll(&mut self) -> FutureResult {
+        match self.rx.try_recv() {
+            Ok(Ok(robj)) => FutureResult::Ready(robj.into_robj()),
+            Ok(Err(err)) => FutureResult::Error(err.into_robj()),
+            Err(TryRecvError::Empty) => FutureResult::Pending,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
 #[extendr]
 struct OAuth2Runtime {
     runtime: tokio::runtime::Runtime,
@@ -150,15 +250,37 @@ struct OAuth2Runtime {
 #[extendr]
 impl OAuth2Runtime {
     // Should return a AsyncFuture with a List containing the access_token
-    // and the refresh token
-    fn request_token(&self, authorization_code: String) -> AsyncFuture {
+    // and the refresh token. `code_verifier` must be the verifier returned
+    // by `get_authorization_url` for this handshake; pass NULL only when
+    // `skip_pkce` is also TRUE, for a provider explicitly configured without
+    // PKCE. `expected_nonce` must be the nonce returned by
+    // `get_authorization_url` for this handshake, and is checked against the
+    // decoded ID token's `nonce` claim to guard against a replayed ID token.
+    fn request_token(
+        &self,
+        authorization_code: String,
+        code_verifier: Option<String>,
+        expected_nonce: Option<String>,
+        skip_pkce: bool,
+    ) -> Result<AsyncFuture> {
+        let pkce_verifier = match (code_verifier, skip_pkce) {
+            (Some(verifier), _) => PkceVerifier::Verifier(verifier),
+            (None, true) => PkceVerifier::SkipPkce,
+            (None, false) => {
+                return Err(Error::from(
+                    "code_verifier is required unless skip_pkce is TRUE".to_string(),
+                ))
+            }
+        };
         let (tx, rx) = tokio::sync::oneshot::channel();
         let client = Arc::clone(&self.client);
         self.runtime.spawn(async move {
-            let response = client.exchange_code(authorization_code).await;
+            let response = client
+                .exchange_code(authorization_code, pkce_verifier, expected_nonce)
+                .await;
             let _ = tx.send(response);
         });
-        AsyncFuture { rx }
+        Ok(AsyncFuture { rx })
     }
 
     // Should return a AsyncFuture with a List containing the new access_token
@@ -183,13 +305,161 @@ impl OAuth2Runtime {
         Ok(res.into_robj())
     }
 
-    fn get_authorization_url(&self) -> String {
-        self.client.get_authorization_url()
+    // Returns a list with `auth_url`, `code_verifier`, `state`, and `nonce`.
+    // The R side must stash `code_verifier`, `state`, and `nonce` (e.g. in
+    // session cookies) and pass them back on the callback request.
+    fn get_authorization_url(&self) -> Robj {
+        let (auth_url, code_verifier, state, nonce) = self.client.get_authorization_url();
+        list!(
+            auth_url = auth_url,
+            code_verifier = code_verifier,
+            state = state,
+            nonce = nonce
+        )
+        .into_robj()
     }
 
     fn get_app_url(&self) -> Robj {
         self.app_url.clone()
     }
+
+    // Revokes an access or refresh token at the provider. Should be polled
+    // until the returned `AsyncUnitFuture` is no longer pending.
+    fn revoke_token(&self, token: String, is_refresh_token: bool) -> AsyncUnitFuture {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let client = Arc::clone(&self.client);
+        let token_type_hint = if is_refresh_token {
+            TokenTypeHint::RefreshToken
+        } else {
+            TokenTypeHint::AccessToken
+        };
+        self.runtime.spawn(async move {
+            let response = client.revoke_token(token, token_type_hint).await;
+            let _ = tx.send(response);
+        });
+        AsyncUnitFuture { rx }
+    }
+
+    fn get_end_session_url(&self) -> String {
+        self.client.end_session_url()
+    }
+
+    // Starts the device authorization grant. Display `user_code` and
+    // `verification_uri` to the user, then poll `poll_device_token` on the
+    // server-suggested `interval` until it's no longer pending.
+    fn request_device_code(&self) -> AsyncDeviceCodeFuture {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let client = Arc::clone(&self.client);
+        self.runtime.spawn(async move {
+            let response = client.request_device_code().await;
+            let _ = tx.send(response);
+        });
+        AsyncDeviceCodeFuture { rx }
+    }
+
+    // Polls the token endpoint for a device code started by
+    // `request_device_code`. On error, check `authorization_pending` and
+    // `slow_down` on the returned error list before giving up: retry after
+    // `interval` seconds while `authorization_pending` is true, and after
+    // `slow_down` extend that wait, per RFC 8628 section 3.5.
+    fn poll_device_token(&self, device_code: String) -> AsyncDeviceTokenFuture {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let client = Arc::clone(&self.client);
+        self.runtime.spawn(async move {
+            let response = client.poll_device_token(device_code).await;
+            let _ = tx.send(response);
+        });
+        AsyncDeviceTokenFuture { rx }
+    }
+}
+
+// Backs `poll_device_token` only, so its errors carry the
+// `authorization_pending`/`slow_down` flags the R poll loop needs; every
+// other login/refresh/revoke/service-account path keeps using its own future
+// (`AsyncFuture`, `AsyncUnitFuture`, `AsyncStringFuture`) and the plain
+// message-only error conversion.
+#[extendr]
+struct AsyncDeviceTokenFuture {
+    rx: oneshot::Receiver<std::result::Result<OAuth2Response, TapLockError>>,
+}
+
+#[extendr]
+impl AsyncDeviceTokenFuture {
+    fn poll(&mut self) -> FutureResult {
+        match self.rx.try_recv() {
+            Ok(Ok(robj)) => FutureResult::Ready(robj.into_robj()),
+            Ok(Err(err)) => FutureResult::Error(device_poll_error_into_robj(err)),
+            Err(TryRecvError::Empty) => FutureResult::Pending,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+#[extendr]
+struct AsyncStringFuture {
+    rx: oneshot::Receiver<std::result::Result<String, TapLockError>>,
+}
+
+#[extendr]
+impl AsyncStringFuture {
+    fn poll(&mut self) -> FutureResult {
+        match self.rx.try_recv() {
+            Ok(Ok(s)) => FutureResult::Ready(s.into_robj()),
+            Ok(Err(err)) => FutureResult::Error(err.into_robj()),
+            Err(TryRecvError::Empty) => FutureResult::Pending,
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+// Headless server-to-server handle for a Google service account: no
+// interactive redirect, just a token getter the R side polls like the other
+// `AsyncFuture`s.
+#[extendr]
+struct ServiceAccountRuntime {
+    runtime: tokio::runtime::Runtime,
+    client: Arc<google::ServiceAccountClient>,
+}
+
+#[extendr]
+impl ServiceAccountRuntime {
+    // Mints (or returns the cached, not-yet-expiring) access token scoped to
+    // `scope`, a space-separated list of Google API scopes.
+    fn get_access_token(&self, scope: String) -> AsyncStringFuture {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let client = Arc::clone(&self.client);
+        self.runtime.spawn(async move {
+            let response = client.access_token(&scope).await;
+            let _ = tx.send(response);
+        });
+        AsyncStringFuture { rx }
+    }
+}
+
+#[extendr]
+fn initialize_google_service_account_runtime(key_path: &str) -> Result<ServiceAccountRuntime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(TapLockError::Io)?;
+
+    let client = Arc::new(google::ServiceAccountClient::from_key_file(key_path)?);
+
+    Ok(ServiceAccountRuntime { runtime, client })
+}
+
+#[extendr]
+fn initialize_google_service_account_from_env_runtime() -> Result<ServiceAccountRuntime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(TapLockError::Io)?;
+
+    let client = Arc::new(google::ServiceAccountClient::from_env()?);
+
+    Ok(ServiceAccountRuntime { runtime, client })
 }
 
 #[extendr]
@@ -357,6 +627,61 @@ fn initialize_keycloak_runtime(
     })
 }
 
+#[extendr]
+fn initialize_oidc_from_env_runtime() -> Result<OAuth2Runtime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(TapLockError::Io)?;
+
+    let client = runtime.block_on(oidc::GenericOidcClient::from_env())?;
+
+    let client = Arc::new(ClientEnum::Oidc(client));
+
+    let app_url_str = std::env::var("TAPLOCK_APP_URL").unwrap_or_default();
+    let app_url = Strings::from(app_url_str).into_robj();
+
+    Ok(OAuth2Runtime {
+        client,
+        runtime,
+        app_url,
+    })
+}
+
+#[extendr]
+fn initialize_oidc_runtime(
+    client_id: &str,
+    client_secret: &str,
+    issuer_url: &str,
+    app_url: &str,
+    use_refresh_token: bool,
+) -> Result<OAuth2Runtime> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(TapLockError::Io)?;
+
+    let client = runtime.block_on(oidc::build_oauth2_state_oidc(
+        client_id,
+        client_secret,
+        issuer_url,
+        app_url,
+        use_refresh_token,
+    ))?;
+
+    let client = Arc::new(ClientEnum::Oidc(client));
+
+    let app_url = Strings::from(app_url).into_robj();
+
+    Ok(OAuth2Runtime {
+        client,
+        runtime,
+        app_url,
+    })
+}
+
 // Macro to generate exports.
 // This ensures exported functions are registered with R.
 // See corresponding C code in `entrypoint.c`.
@@ -366,13 +691,24 @@ extendr_module! {
     fn get_refresh_token_cookie_name;
     fn get_taplock_callback_endpoint;
     fn parse_cookies;
+    fn seal_cookie;
+    fn open_cookie;
     fn initialize_google_runtime;
     fn initialize_google_from_env_runtime;
     fn initialize_entra_id_runtime;
     fn initialize_entra_id_from_env_runtime;
     fn initialize_keycloak_runtime;
     fn initialize_keycloak_from_env_runtime;
+    fn initialize_oidc_runtime;
+    fn initialize_oidc_from_env_runtime;
+    fn initialize_google_service_account_runtime;
+    fn initialize_google_service_account_from_env_runtime;
     impl AsyncFuture;
+    impl AsyncUnitFuture;
+    impl AsyncDeviceCodeFuture;
+    impl AsyncDeviceTokenFuture;
+    impl AsyncStringFuture;
     impl FutureResult;
     impl OAuth2Runtime;
+    impl ServiceAccountRuntime;
 }
@@ -1,38 +1,151 @@
-use super::{ACCESS_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, TAPLOCK_CALLBACK_ENDPOINT};
+use super::{
+    ACCESS_TOKEN_COOKIE_NAME, PKCE_VERIFIER_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME,
+    TAPLOCK_CALLBACK_ENDPOINT,
+};
 
 use axum::{extract::Request, middleware::Next, response::Response};
 
-use super::OAuth2Client;
+use super::{OAuth2Client, PkceVerifier, TokenTypeHint};
 
 use axum::{
     extract::{Query, State},
     response::{IntoResponse, Redirect},
+    Json,
 };
 
 use axum::http::header::{HeaderValue, SET_COOKIE};
-use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite}; // Import for Set-Cookie header
+use axum_extra::extract::cookie::{Cookie, CookieJar, Key, SameSite}; // Import for Set-Cookie header
 
 use serde::Deserialize;
 
 use std::sync::Arc;
 
+// How long the PKCE verifier cookie is allowed to live while the user is away
+// at the IdP completing the redirect dance.
+const PKCE_VERIFIER_COOKIE_EXPIRATION_SECS: i64 = 600;
+
+// How long the CSRF state cookie is allowed to live before a callback
+// presenting it is considered stale and rejected.
+const STATE_COOKIE_EXPIRATION_SECS: i64 = 600;
+const STATE_COOKIE_NAME: &str = "taplock_oauth_state";
+
+// How long the OIDC nonce cookie is allowed to live before a callback
+// presenting an ID token is considered stale and rejected.
+const NONCE_COOKIE_EXPIRATION_SECS: i64 = 600;
+const NONCE_COOKIE_NAME: &str = "taplock_oauth_nonce";
+
+// Records the chosen IdP. Like the session cookies it accompanies, and unlike
+// the short-lived handshake cookies above, it is set without a max-age.
+const IDP_COOKIE_NAME: &str = "taplock_idp";
+
 pub trait AuthState: Clone + Send + Sync + 'static {
-    type Client: OAuth2Client;
-    fn oauth_client(&self) -> Arc<Self::Client>;
+    /// Resolves a registered identity provider by id, e.g. "google" or
+    /// "keycloak". Returns `None` when `idp` isn't registered.
+    fn oauth_client(&self, idp: &str) -> Option<Arc<dyn OAuth2Client>>;
+    /// The provider id to use when none was requested and none is recorded
+    /// in the session's `IDP_COOKIE_NAME` cookie yet.
+    fn default_idp(&self) -> String;
+    /// All registered provider ids, for the picker handler.
+    fn idps(&self) -> Vec<String>;
+    /// When set, cookie values are sealed with this key (AEAD, via
+    /// `cookie`'s private jar) instead of stored in cleartext. Cookies
+    /// written before this was enabled are still read as plaintext.
+    fn cookie_key(&self) -> Option<Key> {
+        None
+    }
+    /// Whether cookies should carry the `Secure` attribute. Defaults to
+    /// `true`; only disable for plain-HTTP local development.
+    fn secure_cookies(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Deserialize)]
 pub struct AuthQuery {
     code: Option<String>,
+    state: Option<String>,
+    idp: Option<String>,
+}
+
+// Encrypts `value` with `key` using an AEAD cipher, scoped to `name` so a
+// sealed cookie can't be replayed under a different cookie name.
+fn seal_value(key: &Key, name: &str, value: &str) -> String {
+    let mut plain_jar = cookie::CookieJar::new();
+    plain_jar
+        .private_mut(key)
+        .add(cookie::Cookie::new(name.to_string(), value.to_string()));
+    plain_jar.get(name).unwrap().value().to_string()
 }
 
-// Helper to create a cookie for setting
-fn create_auth_cookie<'a>(name: &'a str, value: String) -> Cookie<'a> {
+// Decrypts a value previously produced by `seal_value`. Returns `None` if
+// the value is missing, tampered with, or sealed under a different key.
+fn open_value(key: &Key, name: &str, sealed: &str) -> Option<String> {
+    let mut plain_jar = cookie::CookieJar::new();
+    plain_jar.add_original(cookie::Cookie::new(name.to_string(), sealed.to_string()));
+    plain_jar
+        .private(key)
+        .get(name)
+        .map(|c| c.value().to_string())
+}
+
+// Helper to create a cookie for setting, sealing its value when `key` is
+// configured on `AuthState`.
+fn create_auth_cookie<'a>(
+    name: &'a str,
+    value: String,
+    key: Option<&Key>,
+    secure: bool,
+) -> Cookie<'a> {
+    let value = match key {
+        Some(key) => seal_value(key, name, &value),
+        None => value,
+    };
     let mut cookie = Cookie::new(name, value);
     cookie.set_path("/");
     cookie.set_http_only(true);
     cookie.set_same_site(SameSite::Lax);
-    // cookie.set_secure(true); // Enable this if running over HTTPS
+    cookie.set_secure(secure);
+    cookie
+}
+
+// Reads a cookie written by `create_auth_cookie`, opening it when `key` is
+// configured. Falls back to the raw value when it isn't a valid sealed
+// cookie, so plaintext cookies set before encryption was enabled still work.
+fn read_auth_cookie(jar: &CookieJar, name: &str, key: Option<&Key>) -> Option<String> {
+    let raw = jar.get(name).map(|c| c.value().to_string())?;
+    match key {
+        Some(key) => Some(open_value(key, name, &raw).unwrap_or(raw)),
+        None => Some(raw),
+    }
+}
+
+// Helper to create the short-lived cookie that carries the PKCE verifier
+// between the redirect to the IdP and the callback.
+fn create_pkce_verifier_cookie(code_verifier: String, key: Option<&Key>, secure: bool) -> Cookie<'static> {
+    let mut cookie = create_auth_cookie(PKCE_VERIFIER_COOKIE_NAME, code_verifier, key, secure);
+    cookie.set_max_age(Some(cookie::time::Duration::seconds(
+        PKCE_VERIFIER_COOKIE_EXPIRATION_SECS,
+    )));
+    cookie
+}
+
+// Helper to create the short-lived cookie that carries the CSRF state token
+// between the redirect to the IdP and the callback.
+fn create_state_cookie(state: String, key: Option<&Key>, secure: bool) -> Cookie<'static> {
+    let mut cookie = create_auth_cookie(STATE_COOKIE_NAME, state, key, secure);
+    cookie.set_max_age(Some(cookie::time::Duration::seconds(
+        STATE_COOKIE_EXPIRATION_SECS,
+    )));
+    cookie
+}
+
+// Helper to create the short-lived cookie that carries the OIDC nonce
+// between the redirect to the IdP and the callback.
+fn create_nonce_cookie(nonce: String, key: Option<&Key>, secure: bool) -> Cookie<'static> {
+    let mut cookie = create_auth_cookie(NONCE_COOKIE_NAME, nonce, key, secure);
+    cookie.set_max_age(Some(cookie::time::Duration::seconds(
+        NONCE_COOKIE_EXPIRATION_SECS,
+    )));
     cookie
 }
 
@@ -41,6 +154,15 @@ fn remove_auth_cookie<'a>(name: &'a str) -> Cookie<'a> {
     Cookie::build(name).removal().path("/").build()
 }
 
+// Resolves the provider a session is bound to: the requested `idp` (the
+// login request's `idp` query parameter) when present, otherwise whatever is recorded in the IdP cookie,
+// falling back to the app's default provider.
+fn resolve_idp<S: AuthState>(state: &S, jar: &CookieJar, requested: Option<String>) -> String {
+    requested
+        .or_else(|| read_auth_cookie(jar, IDP_COOKIE_NAME, state.cookie_key().as_ref()))
+        .unwrap_or_else(|| state.default_idp())
+}
+
 pub async fn auth_middleware<S>(
     State(state): State<S>,
     jar: CookieJar, // Read-only view of cookies from the request
@@ -50,18 +172,22 @@ pub async fn auth_middleware<S>(
 where
     S: AuthState,
 {
-    let access_token_cookie_val = jar
-        .get(ACCESS_TOKEN_COOKIE_NAME)
-        .map(|c| c.value().to_string());
-    let refresh_token_cookie_val = jar
-        .get(REFRESH_TOKEN_COOKIE_NAME)
-        .map(|c| c.value().to_string());
+    let key = state.cookie_key();
+    let secure = state.secure_cookies();
+    let idp = resolve_idp(&state, &jar, None);
+    let Some(client) = state.oauth_client(&idp) else {
+        tracing::warn!("Unknown identity provider '{}' in session cookie", idp);
+        return Redirect::to(TAPLOCK_CALLBACK_ENDPOINT).into_response();
+    };
+
+    let access_token_cookie_val = read_auth_cookie(&jar, ACCESS_TOKEN_COOKIE_NAME, key.as_ref());
+    let refresh_token_cookie_val = read_auth_cookie(&jar, REFRESH_TOKEN_COOKIE_NAME, key.as_ref());
 
     let mut response;
 
     // --- Validate Access Token ---
     if let Some(access_token) = access_token_cookie_val {
-        match state.oauth_client().decode_access_token(access_token) {
+        match client.decode_access_token(access_token) {
             Ok(_) => {
                 // Access token is valid, proceed with the request
                 return next.run(request).await;
@@ -78,19 +204,19 @@ where
     // --- Access Token is invalid or missing, try to refresh ---
     if let Some(refresh_token) = refresh_token_cookie_val {
         tracing::debug!("Attempting to refresh tokens using refresh token.");
-        match state
-            .oauth_client()
-            .exchange_refresh_token(refresh_token)
-            .await
-        {
+        match client.exchange_refresh_token(refresh_token).await {
             Ok(token_response) => {
                 tracing::debug!("Successfully refreshed tokens.");
                 // Successfully refreshed, run the next middleware/handler and then add new cookies
                 response = next.run(request).await;
 
                 // Set new access token cookie
-                let new_access_cookie =
-                    create_auth_cookie(ACCESS_TOKEN_COOKIE_NAME, token_response.access_token);
+                let new_access_cookie = create_auth_cookie(
+                    ACCESS_TOKEN_COOKIE_NAME,
+                    token_response.access_token,
+                    key.as_ref(),
+                    secure,
+                );
                 response.headers_mut().append(
                     SET_COOKIE,
                     HeaderValue::from_str(&new_access_cookie.to_string()).unwrap(),
@@ -98,8 +224,12 @@ where
 
                 // Set new refresh token cookie or remove old one
                 if let Some(new_refresh_token) = token_response.refresh_token {
-                    let new_refresh_cookie =
-                        create_auth_cookie(REFRESH_TOKEN_COOKIE_NAME, new_refresh_token);
+                    let new_refresh_cookie = create_auth_cookie(
+                        REFRESH_TOKEN_COOKIE_NAME,
+                        new_refresh_token,
+                        key.as_ref(),
+                        secure,
+                    );
                     response.headers_mut().append(
                         SET_COOKIE,
                         HeaderValue::from_str(&new_refresh_cookie.to_string()).unwrap(),
@@ -155,22 +285,102 @@ pub async fn login_handler<S>(
 where
     S: AuthState,
 {
+    let key = state.cookie_key();
+    let secure = state.secure_cookies();
+
     if let Some(code) = query.code {
-        // Handle callback from Keycloak
-        match state.oauth_client().exchange_code(code).await {
+        // Handle callback from the IdP
+        let idp = resolve_idp(&state, &jar, None);
+        let Some(client) = state.oauth_client(&idp) else {
+            tracing::error!("Unknown identity provider '{}' on callback", idp);
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                "Unknown identity provider",
+            )
+                .into_response();
+        };
+
+        let code_verifier = read_auth_cookie(&jar, PKCE_VERIFIER_COOKIE_NAME, key.as_ref());
+        jar = jar.remove(Cookie::build(PKCE_VERIFIER_COOKIE_NAME));
+
+        let expected_state = read_auth_cookie(&jar, STATE_COOKIE_NAME, key.as_ref());
+        jar = jar.remove(Cookie::build(STATE_COOKIE_NAME));
+
+        let expected_nonce = read_auth_cookie(&jar, NONCE_COOKIE_NAME, key.as_ref());
+        jar = jar.remove(Cookie::build(NONCE_COOKIE_NAME));
+
+        let Some(code_verifier) = code_verifier else {
+            tracing::error!("Missing PKCE verifier cookie on callback");
+            return (
+                jar,
+                (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "Missing or expired PKCE verifier",
+                ),
+            )
+                .into_response();
+        };
+
+        let Some(expected_nonce) = expected_nonce else {
+            tracing::error!("Missing OIDC nonce cookie on callback");
+            return (
+                jar,
+                (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "Missing or expired OIDC nonce",
+                ),
+            )
+                .into_response();
+        };
+
+        let (Some(expected_state), Some(received_state)) = (expected_state, query.state) else {
+            tracing::error!("Missing OAuth2 state on callback");
+            jar = jar.remove(Cookie::build(ACCESS_TOKEN_COOKIE_NAME));
+            jar = jar.remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME));
+            return (
+                jar,
+                (axum::http::StatusCode::BAD_REQUEST, "Invalid OAuth2 state"),
+            )
+                .into_response();
+        };
+
+        match client
+            .exchange_code_with_state(
+                code,
+                PkceVerifier::Verifier(code_verifier),
+                Some(expected_nonce),
+                &expected_state,
+                &received_state,
+            )
+            .await
+        {
             Ok(token_response) => {
                 jar = jar.add(create_auth_cookie(
                     ACCESS_TOKEN_COOKIE_NAME,
                     token_response.access_token,
+                    key.as_ref(),
+                    secure,
                 ));
 
                 if let Some(refresh_token) = token_response.refresh_token {
-                    jar = jar.add(create_auth_cookie(REFRESH_TOKEN_COOKIE_NAME, refresh_token));
+                    jar = jar.add(create_auth_cookie(
+                        REFRESH_TOKEN_COOKIE_NAME,
+                        refresh_token,
+                        key.as_ref(),
+                        secure,
+                    ));
                 } else {
                     // If no refresh token is provided on code exchange, ensure any old one is removed.
                     jar = jar.remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME));
                 }
 
+                jar = jar.add(create_auth_cookie(
+                    IDP_COOKIE_NAME,
+                    idp,
+                    key.as_ref(),
+                    secure,
+                ));
+
                 (jar, Redirect::to("/")).into_response()
             }
             Err(e) => {
@@ -189,8 +399,74 @@ where
             }
         }
     } else {
-        // Redirect to Keycloak login
-        let auth_url = state.oauth_client().get_authorization_url();
-        Redirect::to(&auth_url).into_response()
+        // Redirect to the chosen IdP's login page
+        let idp = resolve_idp(&state, &jar, query.idp);
+        let Some(client) = state.oauth_client(&idp) else {
+            tracing::error!("Unknown identity provider '{}'", idp);
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                "Unknown identity provider",
+            )
+                .into_response();
+        };
+
+        let (auth_url, code_verifier, csrf_state, nonce) = client.get_authorization_url();
+        jar = jar.add(create_pkce_verifier_cookie(code_verifier, key.as_ref(), secure));
+        jar = jar.add(create_state_cookie(csrf_state, key.as_ref(), secure));
+        jar = jar.add(create_nonce_cookie(nonce, key.as_ref(), secure));
+        jar = jar.add(create_auth_cookie(
+            IDP_COOKIE_NAME,
+            idp,
+            key.as_ref(),
+            secure,
+        ));
+        (jar, Redirect::to(&auth_url)).into_response()
     }
 }
+
+pub async fn logout_handler<S>(State(state): State<S>, mut jar: CookieJar) -> Response
+where
+    S: AuthState,
+{
+    let key = state.cookie_key();
+    let idp = resolve_idp(&state, &jar, None);
+    let Some(client) = state.oauth_client(&idp) else {
+        jar = jar.remove(Cookie::build(ACCESS_TOKEN_COOKIE_NAME));
+        jar = jar.remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME));
+        jar = jar.remove(Cookie::build(IDP_COOKIE_NAME));
+        return (jar, Redirect::to("/")).into_response();
+    };
+
+    if let Some(access_token) = read_auth_cookie(&jar, ACCESS_TOKEN_COOKIE_NAME, key.as_ref()) {
+        if let Err(e) = client
+            .revoke_token(access_token, TokenTypeHint::AccessToken)
+            .await
+        {
+            tracing::warn!("Failed to revoke access token: {:?}", e);
+        }
+    }
+
+    if let Some(refresh_token) = read_auth_cookie(&jar, REFRESH_TOKEN_COOKIE_NAME, key.as_ref()) {
+        if let Err(e) = client
+            .revoke_token(refresh_token, TokenTypeHint::RefreshToken)
+            .await
+        {
+            tracing::warn!("Failed to revoke refresh token: {:?}", e);
+        }
+    }
+
+    jar = jar.remove(Cookie::build(ACCESS_TOKEN_COOKIE_NAME));
+    jar = jar.remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME));
+    jar = jar.remove(Cookie::build(IDP_COOKIE_NAME));
+
+    (jar, Redirect::to(&client.end_session_url())).into_response()
+}
+
+/// Lists the identity providers registered on `state`, so a login page can
+/// render "log in with Google" / "log in with Keycloak" links.
+pub async fn idp_picker_handler<S>(State(state): State<S>) -> Response
+where
+    S: AuthState,
+{
+    Json(state.idps()).into_response()
+}
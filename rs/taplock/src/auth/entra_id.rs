@@ -0,0 +1,118 @@
+use super::oidc::{build_oauth2_state_oidc, GenericOidcClient};
+use super::{DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier, TokenTypeHint};
+use crate::error::TapLockError;
+
+/// Microsoft Entra ID (Azure AD) is fully OIDC-compliant, so this client is a
+/// thin, tenant-aware wrapper around [`GenericOidcClient`] rather than a
+/// bespoke implementation: the only Entra-specific bit is turning a
+/// `tenant_id` into the tenant's v2.0 issuer URL before handing off to
+/// `build_oauth2_state_oidc`.
+#[derive(Clone, Debug)]
+pub struct AzureADOAuth2Client(GenericOidcClient);
+
+fn issuer_url(tenant_id: &str) -> String {
+    format!("https://login.microsoftonline.com/{tenant_id}/v2.0")
+}
+
+impl AzureADOAuth2Client {
+    /// Initializes an Entra ID client from environment variables
+    ///
+    /// - TAPLOCK_AZURE_AD_CLIENT_ID (OAuth2 client ID)
+    /// - TAPLOCK_AZURE_AD_CLIENT_SECRET (OAuth2 client secret)
+    /// - TAPLOCK_AZURE_AD_TENANT_ID (Entra ID tenant ID)
+    /// - TAPLOCK_APP_URL (Base URL of this application for redirects)
+    /// - TAPLOCK_AZURE_AD_USE_REFRESH_TOKEN (Optional, "true" or "false", defaults to true)
+    ///
+    /// The error returns a vector of strings, either listing missing environment variables
+    /// or describing an error during client initialization.
+    pub async fn from_env() -> Result<Self, TapLockError> {
+        let mut missing_env_vars = Vec::new();
+
+        let get_env_var = |name: &str, missing: &mut Vec<String>| {
+            std::env::var(name).unwrap_or_else(|_| {
+                missing.push(name.to_string());
+                String::new() // Return an empty string as a placeholder if not found
+            })
+        };
+
+        let client_id = get_env_var("TAPLOCK_AZURE_AD_CLIENT_ID", &mut missing_env_vars);
+        let client_secret = get_env_var("TAPLOCK_AZURE_AD_CLIENT_SECRET", &mut missing_env_vars);
+        let tenant_id = get_env_var("TAPLOCK_AZURE_AD_TENANT_ID", &mut missing_env_vars);
+        let app_url = get_env_var("TAPLOCK_APP_URL", &mut missing_env_vars);
+
+        let use_refresh_token = match std::env::var("TAPLOCK_AZURE_AD_USE_REFRESH_TOKEN") {
+            Ok(s) => s.parse::<bool>().unwrap_or_else(|_| {
+                eprintln!("Warning: TAPLOCK_AZURE_AD_USE_REFRESH_TOKEN value '{}' is not a valid boolean. Defaulting to true.", s);
+                true
+            }),
+            Err(_) => true,
+        };
+
+        if !missing_env_vars.is_empty() {
+            return Err(TapLockError::MissingEnv(missing_env_vars));
+        }
+
+        build_oauth2_state_azure_ad(&client_id, &client_secret, &app_url, use_refresh_token, &tenant_id).await
+    }
+}
+
+pub async fn build_oauth2_state_azure_ad(
+    client_id: &str,
+    client_secret: &str,
+    app_url: &str,
+    use_refresh_token: bool,
+    tenant_id: &str,
+) -> Result<AzureADOAuth2Client, TapLockError> {
+    let inner = build_oauth2_state_oidc(
+        client_id,
+        client_secret,
+        &issuer_url(tenant_id),
+        app_url,
+        use_refresh_token,
+    )
+    .await?;
+    Ok(AzureADOAuth2Client(inner))
+}
+
+#[async_trait::async_trait]
+impl OAuth2Client for AzureADOAuth2Client {
+    async fn exchange_refresh_token(
+        &self,
+        refresh_token: String,
+    ) -> Result<OAuth2Response, TapLockError> {
+        self.0.exchange_refresh_token(refresh_token).await
+    }
+    async fn exchange_code(
+        &self,
+        code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
+    ) -> Result<OAuth2Response, TapLockError> {
+        self.0.exchange_code(code, code_verifier, expected_nonce).await
+    }
+    fn decode_access_token(&self, access_token: String) -> Result<OAuth2Response, TapLockError> {
+        self.0.decode_access_token(access_token)
+    }
+    fn get_authorization_url(&self) -> (String, String, String, String) {
+        self.0.get_authorization_url()
+    }
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> Result<(), TapLockError> {
+        self.0.revoke_token(token, token_type_hint).await
+    }
+    fn end_session_url(&self) -> String {
+        self.0.end_session_url()
+    }
+    async fn request_device_code(&self) -> Result<DeviceAuthorizationResponse, TapLockError> {
+        self.0.request_device_code().await
+    }
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> Result<OAuth2Response, TapLockError> {
+        self.0.poll_device_token(device_code).await
+    }
+}
@@ -1,22 +1,31 @@
-use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use oauth2::TokenResponse;
 use oauth2::{
     basic::{
         BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
         BasicTokenType,
     },
-    AuthUrl, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken, RedirectUrl, Scope,
-    StandardRevocableToken, StandardTokenResponse, TokenUrl,
+    AccessToken, AuthUrl, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken,
+    DeviceAuthorizationUrl, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RefreshToken,
+    RevocationUrl, Scope, StandardRevocableToken, StandardTokenResponse, TokenUrl,
 };
 use serde::{Deserialize, Serialize};
 
 use super::jwks::JwksClient;
-use super::{OAuth2Client, OAuth2Response, TAPLOCK_CALLBACK_ENDPOINT};
+use super::jwt::{decode_with_cached_jwk, decode_with_jwks_refresh, map_jwt_error};
+use super::{
+    DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier, TokenTypeHint,
+    TAPLOCK_CALLBACK_ENDPOINT,
+};
 use crate::error::TapLockError;
 
 const AUTH_BASE_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
 const JWKS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+const REVOCATION_URL: &str = "https://oauth2.googleapis.com/revoke";
+const DEVICE_AUTHORIZATION_URL: &str = "https://oauth2.googleapis.com/device/code";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const ISSUER: &str = "https://accounts.google.com";
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct GoogleTokenResponseExtra {
@@ -32,9 +41,9 @@ type GoogleClientFull = Client<
     StandardRevocableToken,
     BasicRevocationErrorResponse,
     oauth2::EndpointSet,
+    oauth2::EndpointSet,
     oauth2::EndpointNotSet,
-    oauth2::EndpointNotSet,
-    oauth2::EndpointNotSet,
+    oauth2::EndpointSet,
     oauth2::EndpointSet,
 >;
 
@@ -48,10 +57,6 @@ pub struct GoogleOAuth2Client {
 }
 
 impl GoogleOAuth2Client {
-    fn get_jwk(&self, kid: &str) -> Option<jsonwebtoken::jwk::Jwk> {
-        self.jwks_client.get_key(kid)
-    }
-
     /// Initializes a Google client from environment variables
     ///
     /// - TAPLOCK_GOOGLE_CLIENT_ID (OAuth2 client ID)
@@ -95,48 +100,31 @@ fn decode_access_token(
     client: &GoogleOAuth2Client,
     access_token: String,
 ) -> Result<OAuth2Response, TapLockError> {
-    let token_trim = access_token.trim_start_matches("Bearer").trim();
-    let jwt_header = decode_header(token_trim)?;
-    let kid = jwt_header.kid.ok_or(TapLockError::KidNotFound)?;
-    let algo = jwt_header.alg;
-    let decoding_key = client.get_jwk(&kid).ok_or(TapLockError::KidNotFound)?;
-    let mut validation = Validation::new(algo);
-    validation.set_audience(&[&client.client_id]);
-    let val = decode::<serde_json::Value>(
-        token_trim,
-        &DecodingKey::from_jwk(&decoding_key)?,
-        &validation,
-    )?;
-
+    let claims = decode_with_cached_jwk(&client.jwks_client, ISSUER, &client.client_id, &access_token)?;
     Ok(OAuth2Response {
         access_token,
         refresh_token: None,
-        fields: val.claims,
+        fields: claims,
     })
 }
 
 async fn decode_token_and_maybe_refresh_jwks(
     client: &GoogleOAuth2Client,
     access_token: String,
+    expected_nonce: Option<&str>,
 ) -> Result<OAuth2Response, TapLockError> {
-    let token_trim = access_token.trim_start_matches("Bearer").trim();
-    let jwt_header = decode_header(token_trim)?;
-    let kid = jwt_header.kid.ok_or(TapLockError::KidNotFound)?;
-
-    let decoding_key = client.jwks_client.get_key_with_refresh(&kid).await?;
-    let algo = jwt_header.alg;
-    let mut validation = Validation::new(algo);
-    validation.set_audience(&[&client.client_id]);
-    let val = decode::<serde_json::Value>(
-        token_trim,
-        &DecodingKey::from_jwk(&decoding_key)?,
-        &validation,
-    )?;
-
+    let claims = decode_with_jwks_refresh(
+        &client.jwks_client,
+        ISSUER,
+        &client.client_id,
+        &access_token,
+        expected_nonce,
+    )
+    .await?;
     Ok(OAuth2Response {
         access_token,
         refresh_token: None,
-        fields: val.claims,
+        fields: claims,
     })
 }
 
@@ -153,6 +141,10 @@ pub async fn build_oauth2_state_google(
         .set_client_secret(ClientSecret::new(client_secret.to_string()))
         .set_auth_uri(AuthUrl::new(AUTH_BASE_URL.to_string())?)
         .set_token_uri(TokenUrl::new(TOKEN_URL.to_string())?)
+        .set_revocation_url(RevocationUrl::new(REVOCATION_URL.to_string())?)
+        .set_device_authorization_url(DeviceAuthorizationUrl::new(
+            DEVICE_AUTHORIZATION_URL.to_string(),
+        )?)
         .set_redirect_uri(RedirectUrl::new(redirect_url)?);
 
     let reqwest_client = reqwest::Client::new();
@@ -185,7 +177,7 @@ impl OAuth2Client for GoogleOAuth2Client {
             .await?;
 
         let access_token = token_result.extra_fields().id_token.clone();
-        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token).await?;
+        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token, None).await?;
         if self.use_refresh_token {
             response.refresh_token = Some(
                 token_result
@@ -199,15 +191,19 @@ impl OAuth2Client for GoogleOAuth2Client {
     async fn exchange_code(
         &self,
         code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
     ) -> std::result::Result<OAuth2Response, TapLockError> {
-        let token_result = self
-            .client
-            .exchange_code(AuthorizationCode::new(code))
-            .request_async(&self.reqwest_client)
-            .await?;
+        let mut request = self.client.exchange_code(AuthorizationCode::new(code));
+        if let PkceVerifier::Verifier(code_verifier) = code_verifier {
+            request = request.set_pkce_verifier(PkceCodeVerifier::new(code_verifier));
+        }
+        let token_result = request.request_async(&self.reqwest_client).await?;
 
         let access_token = token_result.extra_fields().id_token.clone();
-        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token).await?;
+        let mut response =
+            decode_token_and_maybe_refresh_jwks(self, access_token, expected_nonce.as_deref())
+                .await?;
 
         if self.use_refresh_token {
             response.refresh_token = token_result.refresh_token().map(|rt| rt.secret().clone());
@@ -222,14 +218,239 @@ impl OAuth2Client for GoogleOAuth2Client {
         let response = decode_access_token(self, access_token)?;
         Ok(response)
     }
-    fn get_authorization_url(&self) -> String {
-        let (auth_url, _csrf_token) = self
+    fn get_authorization_url(&self) -> (String, String, String, String) {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let nonce = CsrfToken::new_random().secret().clone();
+        let (auth_url, csrf_token) = self
             .client
             .authorize_url(CsrfToken::new_random)
             .add_extra_param("access_type", "offline")
             .add_extra_param("prompt", "consent")
+            .add_extra_param("nonce", &nonce)
             .add_scopes(["openid", "email", "profile"].map(|s| Scope::new(s.into())))
+            .set_pkce_challenge(pkce_challenge)
             .url();
-        auth_url.to_string()
+        (
+            auth_url.to_string(),
+            pkce_verifier.secret().clone(),
+            csrf_token.secret().clone(),
+            nonce,
+        )
+    }
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> std::result::Result<(), TapLockError> {
+        let revocable_token = match token_type_hint {
+            TokenTypeHint::AccessToken => {
+                StandardRevocableToken::AccessToken(AccessToken::new(token))
+            }
+            TokenTypeHint::RefreshToken => {
+                StandardRevocableToken::RefreshToken(RefreshToken::new(token))
+            }
+        };
+        self.client
+            .revoke_token(revocable_token)?
+            .request_async(&self.reqwest_client)
+            .await?;
+        Ok(())
+    }
+    fn end_session_url(&self) -> String {
+        // Google has no hosted end-session endpoint; the access/refresh
+        // tokens are revoked directly, so there's nowhere else to send the
+        // browser but back into the app.
+        "/".to_string()
+    }
+    async fn request_device_code(
+        &self,
+    ) -> std::result::Result<DeviceAuthorizationResponse, TapLockError> {
+        let details = self
+            .client
+            .exchange_device_code()
+            .add_scopes(["openid", "email", "profile"].map(|s| Scope::new(s.into())))
+            .request_async(&self.reqwest_client)
+            .await?;
+
+        Ok(DeviceAuthorizationResponse {
+            device_code: details.device_code().secret().clone(),
+            user_code: details.user_code().secret().clone(),
+            verification_uri: details.verification_uri().to_string(),
+            verification_uri_complete: details
+                .verification_uri_complete()
+                .map(|uri| uri.secret().clone()),
+            expires_in: details.expires_in().as_secs(),
+            interval: details.interval().as_secs(),
+        })
+    }
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        let params = [
+            ("grant_type", DEVICE_GRANT_TYPE),
+            ("device_code", device_code.as_str()),
+            ("client_id", self.client_id.as_str()),
+        ];
+
+        let resp = self
+            .reqwest_client
+            .post(TOKEN_URL)
+            .form(&params)
+            .send()
+            .await
+            .map_err(TapLockError::Reqwest)?;
+
+        let status = resp.status();
+        let body: serde_json::Value = resp.json().await.map_err(TapLockError::Reqwest)?;
+
+        if !status.is_success() {
+            return Err(match body.get("error").and_then(|e| e.as_str()) {
+                Some("authorization_pending") => TapLockError::AuthorizationPending,
+                Some("slow_down") => TapLockError::SlowDown,
+                _ => TapLockError::new(format!("Device token request failed: {body}")),
+            });
+        }
+
+        let access_token = body
+            .get("id_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| TapLockError::new("Device token response is missing id_token"))?
+            .to_string();
+
+        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token, None).await?;
+        if self.use_refresh_token {
+            response.refresh_token = body
+                .get("refresh_token")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+        }
+        Ok(response)
+    }
+}
+
+// Minted service-account access tokens are cached until `exp` minus this
+// skew, so a caller minting tokens back-to-back doesn't round-trip to the
+// token endpoint every time.
+const SERVICE_ACCOUNT_TOKEN_SKEW_SECS: i64 = 60;
+const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// The fields tapLock-rs needs out of a Google service-account JSON key
+/// (the rest of the file, e.g. `project_id`, is ignored).
+#[derive(Debug, Deserialize, Clone)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    private_key_id: String,
+}
+
+#[derive(Serialize)]
+struct ServiceAccountClaims {
+    iss: String,
+    scope: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// Server-to-server ("2-legged") OAuth2 for calling Google APIs as the
+/// service account itself, with no user in the loop: a signed JWT assertion
+/// is exchanged at the token endpoint for a bearer access token, per
+/// https://developers.google.com/identity/protocols/oauth2/service-account.
+#[derive(Clone, Debug)]
+pub struct ServiceAccountClient {
+    reqwest_client: reqwest::Client,
+    key: ServiceAccountKey,
+    cached_tokens: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, (String, i64)>>>,
+}
+
+impl ServiceAccountClient {
+    /// Loads a service-account key from `GOOGLE_APPLICATION_CREDENTIALS`,
+    /// the same env var the Google client libraries use.
+    pub fn from_env() -> Result<Self, TapLockError> {
+        let path = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+            .map_err(|_| TapLockError::MissingEnv(vec!["GOOGLE_APPLICATION_CREDENTIALS".into()]))?;
+        Self::from_key_file(&path)
+    }
+
+    /// Loads a service-account key from an explicit path, for callers that
+    /// don't want to rely on `GOOGLE_APPLICATION_CREDENTIALS`.
+    pub fn from_key_file(path: &str) -> Result<Self, TapLockError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| TapLockError::new(format!("Failed to read {path}: {e}")))?;
+        let key: ServiceAccountKey = serde_json::from_str(&contents)
+            .map_err(|e| TapLockError::new(format!("Failed to parse {path}: {e}")))?;
+        Ok(Self {
+            reqwest_client: reqwest::Client::new(),
+            key,
+            cached_tokens: std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+        })
+    }
+
+    /// Returns a bearer access token scoped to `scope` (a space-separated
+    /// list of Google API scopes), re-minting it once the cached one for
+    /// that scope is within `SERVICE_ACCOUNT_TOKEN_SKEW_SECS` of expiring.
+    /// Tokens are cached per scope, since a token minted for one scope isn't
+    /// valid for another.
+    pub async fn access_token(&self, scope: &str) -> Result<String, TapLockError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        if let Some((token, exp)) = self.cached_tokens.lock().unwrap().get(scope).cloned() {
+            if now < exp - SERVICE_ACCOUNT_TOKEN_SKEW_SECS {
+                return Ok(token);
+            }
+        }
+
+        let claims = ServiceAccountClaims {
+            iss: self.key.client_email.clone(),
+            scope: scope.to_string(),
+            aud: TOKEN_URL.to_string(),
+            iat: now,
+            exp: now + 3600,
+        };
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.key.private_key_id.clone());
+        let assertion = encode(
+            &header,
+            &claims,
+            &EncodingKey::from_rsa_pem(self.key.private_key.as_bytes())
+                .map_err(|e| TapLockError::new(format!("Invalid service-account private key: {e}")))?,
+        )
+        .map_err(map_jwt_error)?;
+
+        let params = [("grant_type", JWT_BEARER_GRANT_TYPE), ("assertion", &assertion)];
+        let resp = self
+            .reqwest_client
+            .post(TOKEN_URL)
+            .form(&params)
+            .send()
+            .await
+            .map_err(TapLockError::Reqwest)?;
+
+        let status = resp.status();
+        let body: serde_json::Value = resp.json().await.map_err(TapLockError::Reqwest)?;
+
+        if !status.is_success() {
+            return Err(TapLockError::new(format!(
+                "Service-account token request failed: {body}"
+            )));
+        }
+
+        let access_token = body
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| TapLockError::new("Token response is missing access_token"))?
+            .to_string();
+        let expires_in = body.get("expires_in").and_then(|v| v.as_i64()).unwrap_or(3600);
+
+        self.cached_tokens
+            .lock()
+            .unwrap()
+            .insert(scope.to_string(), (access_token.clone(), now + expires_in));
+
+        Ok(access_token)
     }
 }
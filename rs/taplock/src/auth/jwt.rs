@@ -0,0 +1,96 @@
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+
+use super::constant_time_eq;
+use super::jwks::JwksClient;
+use crate::error::TapLockError;
+
+/// Maps a `jsonwebtoken` decode failure onto the specific `TapLockError`
+/// variant callers (e.g. `auth_middleware`) need to decide between a token
+/// refresh and a full re-login. Shared by every provider that decodes a JWT
+/// access/ID token, so the mapping can't drift between them.
+pub(crate) fn map_jwt_error(e: jsonwebtoken::errors::Error) -> TapLockError {
+    use jsonwebtoken::errors::ErrorKind;
+    match e.kind() {
+        ErrorKind::ExpiredSignature => TapLockError::TokenExpired,
+        ErrorKind::ImmatureSignature => TapLockError::TokenNotYetValid,
+        ErrorKind::InvalidSignature => TapLockError::InvalidSignature,
+        ErrorKind::InvalidIssuer => TapLockError::InvalidIssuer,
+        ErrorKind::InvalidAudience => TapLockError::InvalidAudience,
+        _ => TapLockError::Jwt(e),
+    }
+}
+
+/// Algorithms this crate will ever validate a signature with. `decode_claims`
+/// cross-checks the token's header `alg` against this list rather than
+/// trusting it outright — an attacker-controlled header is not allowed to
+/// pick its own validation algorithm (e.g. downgrading to `none` or a weak
+/// HMAC variant).
+const ALLOWED_ALGORITHMS: &[jsonwebtoken::Algorithm] =
+    &[jsonwebtoken::Algorithm::RS256, jsonwebtoken::Algorithm::ES256];
+
+fn decode_claims(
+    token: &str,
+    decoding_key: &jsonwebtoken::jwk::Jwk,
+    algo: jsonwebtoken::Algorithm,
+    issuer: &str,
+    audience: &str,
+) -> Result<serde_json::Value, TapLockError> {
+    if !ALLOWED_ALGORITHMS.contains(&algo) {
+        return Err(TapLockError::InvalidSignature);
+    }
+    let mut validation = Validation::new(algo);
+    validation.set_audience(&[audience]);
+    validation.set_issuer(&[issuer]);
+    validation.validate_nbf = true;
+    let val = decode::<serde_json::Value>(
+        token,
+        &DecodingKey::from_jwk(decoding_key)?,
+        &validation,
+    )
+    .map_err(map_jwt_error)?;
+    Ok(val.claims)
+}
+
+/// Decodes and validates `access_token` against a JWK already cached in
+/// `jwks_client` (no refresh on a `kid` miss). Used by a provider's
+/// synchronous `decode_access_token`.
+pub(crate) fn decode_with_cached_jwk(
+    jwks_client: &JwksClient,
+    issuer: &str,
+    audience: &str,
+    access_token: &str,
+) -> Result<serde_json::Value, TapLockError> {
+    let token_trim = access_token.trim_start_matches("Bearer").trim();
+    let jwt_header = decode_header(token_trim)?;
+    let kid = jwt_header.kid.ok_or(TapLockError::KidNotFound)?;
+    let decoding_key = jwks_client.get_key(&kid).ok_or(TapLockError::KidNotFound)?;
+    decode_claims(token_trim, &decoding_key, jwt_header.alg, issuer, audience)
+}
+
+/// Same as `decode_with_cached_jwk`, but refreshes the JWKS cache on a `kid`
+/// miss before giving up. Used by a provider's `exchange_code`/
+/// `exchange_refresh_token`/`poll_device_token`. When `expected_nonce` is
+/// `Some`, it's checked against the decoded token's `nonce` claim in
+/// constant time, guarding against a replayed ID token.
+pub(crate) async fn decode_with_jwks_refresh(
+    jwks_client: &JwksClient,
+    issuer: &str,
+    audience: &str,
+    access_token: &str,
+    expected_nonce: Option<&str>,
+) -> Result<serde_json::Value, TapLockError> {
+    let token_trim = access_token.trim_start_matches("Bearer").trim();
+    let jwt_header = decode_header(token_trim)?;
+    let kid = jwt_header.kid.ok_or(TapLockError::KidNotFound)?;
+    let decoding_key = jwks_client.get_key_with_refresh(&kid).await?;
+    let claims = decode_claims(token_trim, &decoding_key, jwt_header.alg, issuer, audience)?;
+
+    if let Some(expected_nonce) = expected_nonce {
+        let actual_nonce = claims.get("nonce").and_then(|v| v.as_str()).unwrap_or("");
+        if !constant_time_eq(expected_nonce, actual_nonce) {
+            return Err(TapLockError::new("ID token nonce mismatch"));
+        }
+    }
+
+    Ok(claims)
+}
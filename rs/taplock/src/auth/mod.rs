@@ -4,11 +4,14 @@ pub mod axum;
 pub mod entra_id;
 pub mod google;
 pub mod jwks;
+mod jwt;
 pub mod keycloak;
+pub mod oidc;
 
 pub const ACCESS_TOKEN_COOKIE_NAME: &str = "taplock_access_token";
 pub const REFRESH_TOKEN_COOKIE_NAME: &str = "taplock_refresh_token";
 pub const TAPLOCK_CALLBACK_ENDPOINT: &str = "/.taplock/callback";
+pub const PKCE_VERIFIER_COOKIE_NAME: &str = "taplock_pkce_verifier";
 
 // TODO: Pasar todo esto a tapLock-rs distribuible
 
@@ -22,13 +25,155 @@ pub struct OAuth2Response {
     pub fields: serde_json::Value,
 }
 
+/// Which kind of token is being revoked, per RFC 7009's `token_type_hint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenTypeHint {
+    AccessToken,
+    RefreshToken,
+}
+
+/// Whether `exchange_code` replays a PKCE code verifier. `None` would make
+/// "skip PKCE" indistinguishable from "the caller forgot to pass one", so
+/// skipping it is a named variant instead: a public-client flow (the `axum`
+/// middleware, and any Python/R caller that didn't opt out) always has to
+/// supply `Verifier`, while `SkipPkce` is something a confidential-client
+/// caller has to choose on purpose.
+#[derive(Debug, Clone)]
+pub enum PkceVerifier {
+    /// Replay the verifier returned by `get_authorization_url`.
+    Verifier(String),
+    /// Deliberately proceed without PKCE (e.g. a confidential-client
+    /// exchange where the client secret already authenticates the request).
+    SkipPkce,
+}
+
+/// Details returned by a provider's device authorization endpoint, to be
+/// displayed to the user so they can approve the sign-in on another device.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+/// Compares two strings in constant time, so comparing a CSRF `state` (or
+/// any other secret-adjacent value) against an attacker-controlled input
+/// doesn't leak a timing side-channel.
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Name of the env var holding the key material for `seal_cookie`/
+/// `open_cookie`. Unlike `axum::AuthState::cookie_key`, which is configured
+/// per-app, this is a single process-wide secret for callers (e.g. the R
+/// bindings) that manage their own cookie jar instead of going through the
+/// `axum` middleware.
+pub const COOKIE_SECRET_ENV_VAR: &str = "TAPLOCK_COOKIE_SECRET";
+
+fn cookie_secret_key() -> Result<cookie::Key, TapLockError> {
+    let secret = std::env::var(COOKIE_SECRET_ENV_VAR)
+        .map_err(|_| TapLockError::new(format!("{COOKIE_SECRET_ENV_VAR} is not set")))?;
+    Ok(cookie::Key::derive_from(secret.as_bytes()))
+}
+
+/// Encrypts `value` with the key derived from `TAPLOCK_COOKIE_SECRET`, using
+/// `cookie`'s AEAD "private" jar (a random per-value nonce is prepended and
+/// the result base64-encoded with the standard alphabet, not the URL-safe
+/// one), scoped to `name` so a sealed cookie can't be replayed under a
+/// different cookie name.
+pub fn seal_cookie(name: &str, value: &str) -> Result<String, TapLockError> {
+    let key = cookie_secret_key()?;
+    let mut plain_jar = cookie::CookieJar::new();
+    plain_jar
+        .private_mut(&key)
+        .add(cookie::Cookie::new(name.to_string(), value.to_string()));
+    Ok(plain_jar.get(name).unwrap().value().to_string())
+}
+
+/// Decrypts a value previously produced by `seal_cookie`. Rejects tampered
+/// values, values sealed under a different key, or values sealed under a
+/// different `name`, rather than returning garbage.
+pub fn open_cookie(name: &str, sealed: &str) -> Result<String, TapLockError> {
+    let key = cookie_secret_key()?;
+    let mut plain_jar = cookie::CookieJar::new();
+    plain_jar.add_original(cookie::Cookie::new(name.to_string(), sealed.to_string()));
+    plain_jar
+        .private(&key)
+        .get(name)
+        .map(|c| c.value().to_string())
+        .ok_or_else(|| TapLockError::new("cookie value is invalid, tampered with, or sealed under a different key"))
+}
+
 #[async_trait::async_trait]
 pub trait OAuth2Client: Send + Sync {
     async fn exchange_refresh_token(
         &self,
         refresh_token: String,
     ) -> Result<OAuth2Response, TapLockError>;
-    async fn exchange_code(&self, code: String) -> Result<OAuth2Response, TapLockError>;
+    /// Exchanges an authorization code for tokens. `code_verifier` is the
+    /// PKCE verifier returned by `get_authorization_url` and should be
+    /// `PkceVerifier::Verifier` for any public-client flow; callers pass
+    /// `PkceVerifier::SkipPkce` only when they deliberately skip PKCE (e.g. a
+    /// confidential-client exchange). `expected_nonce`, when `Some`, must
+    /// equal the decoded ID token's `nonce` claim or the exchange fails,
+    /// guarding against a replayed ID token from another login attempt.
+    async fn exchange_code(
+        &self,
+        code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
+    ) -> Result<OAuth2Response, TapLockError>;
+    /// Verifies the CSRF `state` echoed back on the callback against
+    /// `expected_state` (the value minted by `get_authorization_url`) in
+    /// constant time, before exchanging `code` for tokens. Returns
+    /// `TapLockError::new("CSRF state mismatch")` on a mismatch.
+    async fn exchange_code_with_state(
+        &self,
+        code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
+        expected_state: &str,
+        received_state: &str,
+    ) -> Result<OAuth2Response, TapLockError> {
+        if !constant_time_eq(expected_state, received_state) {
+            return Err(TapLockError::new("CSRF state mismatch"));
+        }
+        self.exchange_code(code, code_verifier, expected_nonce).await
+    }
     fn decode_access_token(&self, access_token: String) -> Result<OAuth2Response, TapLockError>;
-    fn get_authorization_url(&self) -> String;
+    /// Returns the authorization URL to redirect the user to, together with
+    /// the PKCE code verifier that must be replayed to `exchange_code`, the
+    /// CSRF `state` token that must be echoed back on the callback, and a
+    /// `nonce` that must be replayed as `exchange_code`'s `expected_nonce`
+    /// to guard against ID token replay.
+    fn get_authorization_url(&self) -> (String, String, String, String);
+    /// Revokes a token at the provider per RFC 7009 (or the provider's
+    /// closest equivalent), so a compromised or logged-out session can't be
+    /// replayed at the IdP.
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> Result<(), TapLockError>;
+    /// Where to send the browser after `logout_handler` has revoked the
+    /// session's tokens and cleared its cookies.
+    fn end_session_url(&self) -> String;
+    /// Starts the OAuth2 Device Authorization Grant for headless clients
+    /// that can't receive a browser redirect (RStudio Server, Jupyter, CI).
+    async fn request_device_code(&self) -> Result<DeviceAuthorizationResponse, TapLockError>;
+    /// Polls the token endpoint for a device code started by
+    /// `request_device_code`. Returns `TapLockError::AuthorizationPending`
+    /// or `TapLockError::SlowDown` while the user hasn't approved the
+    /// request yet, so callers can retry on the server-suggested interval.
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> Result<OAuth2Response, TapLockError>;
 }
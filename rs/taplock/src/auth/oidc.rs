@@ -0,0 +1,418 @@
+use oauth2::TokenResponse;
+use oauth2::{
+    basic::{
+        BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+        BasicTokenType,
+    },
+    AuthUrl, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, Scope, StandardRevocableToken, StandardTokenResponse, TokenUrl,
+};
+use serde::{Deserialize, Serialize};
+
+use super::jwks::JwksClient;
+use super::jwt::{decode_with_cached_jwk, decode_with_jwks_refresh};
+use super::{
+    DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier, TokenTypeHint,
+    TAPLOCK_CALLBACK_ENDPOINT,
+};
+use crate::error::TapLockError;
+
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Subset of a `.well-known/openid-configuration` document that the generic
+/// client needs to wire itself up. Any other provider metadata is ignored.
+#[derive(Debug, Deserialize)]
+struct OidcDiscoveryDocument {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    revocation_endpoint: Option<String>,
+    device_authorization_endpoint: Option<String>,
+    end_session_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct OidcTokenResponseExtra {
+    id_token: String,
+}
+
+impl oauth2::ExtraTokenFields for OidcTokenResponseExtra {}
+
+// `device_authorization_endpoint` and `revocation_endpoint` are OPTIONAL per
+// RFC 8414/OIDC discovery, so `HasDeviceAuthUrl`/`HasRevocationUrl` stay
+// `EndpointNotSet` here: the `oauth2` crate's typestate can't express "set
+// only if the discovery document advertised it" on a single concrete type.
+// Both endpoints are instead carried as plain `Option<String>` fields below
+// and requests against them are built by hand in `request_device_code`/
+// `revoke_token`, the same way `end_session_endpoint` is handled.
+type OidcClientFull = Client<
+    BasicErrorResponse,
+    StandardTokenResponse<OidcTokenResponseExtra, BasicTokenType>,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+    oauth2::EndpointSet,
+    oauth2::EndpointNotSet,
+    oauth2::EndpointNotSet,
+    oauth2::EndpointNotSet,
+    oauth2::EndpointSet,
+>;
+
+/// A generic OpenID Connect client for providers that aren't worth a
+/// bespoke module, configured entirely from their issuer's discovery
+/// document instead of hardcoded endpoint constants.
+#[derive(Clone, Debug)]
+pub struct GenericOidcClient {
+    reqwest_client: reqwest::Client,
+    client: OidcClientFull,
+    client_id: String,
+    client_secret: String,
+    issuer: String,
+    end_session_endpoint: Option<String>,
+    device_authorization_endpoint: Option<String>,
+    revocation_endpoint: Option<String>,
+    token_endpoint: String,
+    jwks_client: JwksClient,
+    use_refresh_token: bool,
+}
+
+impl GenericOidcClient {
+    /// Initializes a generic OIDC client from environment variables
+    ///
+    /// - TAPLOCK_OIDC_CLIENT_ID (OAuth2 client ID)
+    /// - TAPLOCK_OIDC_CLIENT_SECRET (OAuth2 client secret)
+    /// - TAPLOCK_OIDC_ISSUER_URL (the provider's issuer, used to fetch
+    ///   `{issuer}/.well-known/openid-configuration`)
+    /// - TAPLOCK_APP_URL (Base URL of this application for redirects)
+    /// - TAPLOCK_OIDC_USE_REFRESH_TOKEN (Optional, "true" or "false", defaults to true)
+    ///
+    /// The error returns a vector of strings, either listing missing environment variables
+    /// or describing an error during client initialization.
+    pub async fn from_env() -> Result<Self, TapLockError> {
+        let mut missing_env_vars = Vec::new();
+
+        let get_env_var = |name: &str, missing: &mut Vec<String>| {
+            std::env::var(name).unwrap_or_else(|_| {
+                missing.push(name.to_string());
+                String::new() // Return an empty string as a placeholder if not found
+            })
+        };
+
+        let client_id = get_env_var("TAPLOCK_OIDC_CLIENT_ID", &mut missing_env_vars);
+        let client_secret = get_env_var("TAPLOCK_OIDC_CLIENT_SECRET", &mut missing_env_vars);
+        let issuer_url = get_env_var("TAPLOCK_OIDC_ISSUER_URL", &mut missing_env_vars);
+        let app_url = get_env_var("TAPLOCK_APP_URL", &mut missing_env_vars);
+
+        let use_refresh_token = match std::env::var("TAPLOCK_OIDC_USE_REFRESH_TOKEN") {
+            Ok(s) => s.parse::<bool>().unwrap_or_else(|_| {
+                eprintln!("Warning: TAPLOCK_OIDC_USE_REFRESH_TOKEN value '{}' is not a valid boolean. Defaulting to true.", s);
+                true
+            }),
+            Err(_) => true,
+        };
+
+        if !missing_env_vars.is_empty() {
+            return Err(TapLockError::MissingEnv(missing_env_vars));
+        }
+
+        build_oauth2_state_oidc(&client_id, &client_secret, &issuer_url, &app_url, use_refresh_token).await
+    }
+}
+
+fn decode_access_token(
+    client: &GenericOidcClient,
+    access_token: String,
+) -> Result<OAuth2Response, TapLockError> {
+    let claims = decode_with_cached_jwk(&client.jwks_client, &client.issuer, &client.client_id, &access_token)?;
+    Ok(OAuth2Response {
+        access_token,
+        refresh_token: None,
+        fields: claims,
+    })
+}
+
+async fn decode_token_and_maybe_refresh_jwks(
+    client: &GenericOidcClient,
+    access_token: String,
+    expected_nonce: Option<&str>,
+) -> Result<OAuth2Response, TapLockError> {
+    let claims = decode_with_jwks_refresh(
+        &client.jwks_client,
+        &client.issuer,
+        &client.client_id,
+        &access_token,
+        expected_nonce,
+    )
+    .await?;
+    Ok(OAuth2Response {
+        access_token,
+        refresh_token: None,
+        fields: claims,
+    })
+}
+
+/// Fetches and parses `{issuer}/.well-known/openid-configuration`.
+async fn discover(
+    reqwest_client: &reqwest::Client,
+    issuer_url: &str,
+) -> Result<OidcDiscoveryDocument, TapLockError> {
+    let issuer_url = issuer_url.trim_end_matches('/');
+    let discovery_url = format!("{issuer_url}/.well-known/openid-configuration");
+    let document = reqwest_client
+        .get(discovery_url)
+        .send()
+        .await
+        .map_err(TapLockError::Reqwest)?
+        .json::<OidcDiscoveryDocument>()
+        .await
+        .map_err(TapLockError::Reqwest)?;
+    Ok(document)
+}
+
+pub async fn build_oauth2_state_oidc(
+    client_id: &str,
+    client_secret: &str,
+    issuer_url: &str,
+    app_url: &str,
+    use_refresh_token: bool,
+) -> std::result::Result<GenericOidcClient, TapLockError> {
+    let app_url = app_url.trim_end_matches('/');
+    let redirect_url = format!("{app_url}{TAPLOCK_CALLBACK_ENDPOINT}");
+
+    let reqwest_client = reqwest::Client::new();
+    let document = discover(&reqwest_client, issuer_url).await?;
+
+    let client = Client::new(ClientId::new(client_id.to_string()))
+        .set_client_secret(ClientSecret::new(client_secret.to_string()))
+        .set_auth_uri(AuthUrl::new(document.authorization_endpoint)?)
+        .set_token_uri(TokenUrl::new(document.token_endpoint.clone())?)
+        .set_redirect_uri(RedirectUrl::new(redirect_url)?);
+
+    let jwks_client = JwksClient::new(document.jwks_uri, reqwest_client.clone()).await?;
+
+    Ok(GenericOidcClient {
+        reqwest_client,
+        client,
+        jwks_client,
+        client_id: client_id.to_string(),
+        client_secret: client_secret.to_string(),
+        issuer: document.issuer,
+        end_session_endpoint: document.end_session_endpoint,
+        device_authorization_endpoint: document.device_authorization_endpoint,
+        revocation_endpoint: document.revocation_endpoint,
+        token_endpoint: document.token_endpoint,
+        use_refresh_token,
+    })
+}
+
+#[async_trait::async_trait]
+impl OAuth2Client for GenericOidcClient {
+    async fn exchange_refresh_token(
+        &self,
+        refresh_token: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        if !self.use_refresh_token {
+            return Err(TapLockError::new("Refresh token is disabled"));
+        }
+        let token_result = self
+            .client
+            .exchange_refresh_token(&oauth2::RefreshToken::new(refresh_token.to_string()))
+            .add_scopes(["openid", "email", "profile"].map(|s| Scope::new(s.into())))
+            .request_async(&self.reqwest_client)
+            .await?;
+
+        let access_token = token_result.extra_fields().id_token.clone();
+        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token, None).await?;
+        if self.use_refresh_token {
+            response.refresh_token = Some(
+                token_result
+                    .refresh_token()
+                    .map(|rt| rt.secret().clone())
+                    .unwrap_or(refresh_token),
+            );
+        }
+        Ok(response)
+    }
+    async fn exchange_code(
+        &self,
+        code: String,
+        code_verifier: PkceVerifier,
+        expected_nonce: Option<String>,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        let mut request = self.client.exchange_code(AuthorizationCode::new(code));
+        if let PkceVerifier::Verifier(code_verifier) = code_verifier {
+            request = request.set_pkce_verifier(PkceCodeVerifier::new(code_verifier));
+        }
+        let token_result = request.request_async(&self.reqwest_client).await?;
+
+        let access_token = token_result.extra_fields().id_token.clone();
+        let mut response =
+            decode_token_and_maybe_refresh_jwks(self, access_token, expected_nonce.as_deref())
+                .await?;
+
+        if self.use_refresh_token {
+            response.refresh_token = token_result.refresh_token().map(|rt| rt.secret().clone());
+        }
+
+        Ok(response)
+    }
+    fn decode_access_token(
+        &self,
+        access_token: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        let response = decode_access_token(self, access_token)?;
+        Ok(response)
+    }
+    fn get_authorization_url(&self) -> (String, String, String, String) {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let nonce = CsrfToken::new_random().secret().clone();
+        let (auth_url, csrf_token) = self
+            .client
+            .authorize_url(CsrfToken::new_random)
+            .add_extra_param("nonce", &nonce)
+            .add_scopes(["openid", "email", "profile"].map(|s| Scope::new(s.into())))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+        (
+            auth_url.to_string(),
+            pkce_verifier.secret().clone(),
+            csrf_token.secret().clone(),
+            nonce,
+        )
+    }
+    async fn revoke_token(
+        &self,
+        token: String,
+        token_type_hint: TokenTypeHint,
+    ) -> std::result::Result<(), TapLockError> {
+        let Some(revocation_endpoint) = &self.revocation_endpoint else {
+            return Err(TapLockError::new(format!(
+                "Token revocation is not supported by '{}': its discovery document has no revocation_endpoint",
+                self.issuer
+            )));
+        };
+        let hint = match token_type_hint {
+            TokenTypeHint::AccessToken => "access_token",
+            TokenTypeHint::RefreshToken => "refresh_token",
+        };
+        let params = [("token", token.as_str()), ("token_type_hint", hint)];
+
+        let resp = self
+            .reqwest_client
+            .post(revocation_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&params)
+            .send()
+            .await
+            .map_err(TapLockError::Reqwest)?;
+
+        if !resp.status().is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(TapLockError::new(format!(
+                "Token revocation request failed: {body}"
+            )));
+        }
+        Ok(())
+    }
+    fn end_session_url(&self) -> String {
+        match &self.end_session_endpoint {
+            Some(endpoint) => format!("{endpoint}?client_id={}", self.client_id),
+            None => "/".to_string(),
+        }
+    }
+    async fn request_device_code(
+        &self,
+    ) -> std::result::Result<DeviceAuthorizationResponse, TapLockError> {
+        let Some(device_authorization_endpoint) = &self.device_authorization_endpoint else {
+            return Err(TapLockError::new(format!(
+                "The device authorization grant is not supported by '{}': its discovery document has no device_authorization_endpoint",
+                self.issuer
+            )));
+        };
+        let params = [
+            ("client_id", self.client_id.as_str()),
+            ("scope", "openid email profile"),
+        ];
+
+        let resp = self
+            .reqwest_client
+            .post(device_authorization_endpoint)
+            .form(&params)
+            .send()
+            .await
+            .map_err(TapLockError::Reqwest)?;
+
+        let status = resp.status();
+        let body: serde_json::Value = resp.json().await.map_err(TapLockError::Reqwest)?;
+
+        if !status.is_success() {
+            return Err(TapLockError::new(format!(
+                "Device authorization request failed: {body}"
+            )));
+        }
+
+        let field = |name: &str| {
+            body.get(name)
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .ok_or_else(|| TapLockError::new(format!("Device authorization response is missing {name}")))
+        };
+
+        Ok(DeviceAuthorizationResponse {
+            device_code: field("device_code")?,
+            user_code: field("user_code")?,
+            verification_uri: field("verification_uri")?,
+            verification_uri_complete: body
+                .get("verification_uri_complete")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            expires_in: body.get("expires_in").and_then(|v| v.as_u64()).unwrap_or(1800),
+            interval: body.get("interval").and_then(|v| v.as_u64()).unwrap_or(5),
+        })
+    }
+    async fn poll_device_token(
+        &self,
+        device_code: String,
+    ) -> std::result::Result<OAuth2Response, TapLockError> {
+        let params = [
+            ("grant_type", DEVICE_GRANT_TYPE),
+            ("device_code", device_code.as_str()),
+            ("client_id", self.client_id.as_str()),
+        ];
+
+        let resp = self
+            .reqwest_client
+            .post(&self.token_endpoint)
+            .form(&params)
+            .send()
+            .await
+            .map_err(TapLockError::Reqwest)?;
+
+        let status = resp.status();
+        let body: serde_json::Value = resp.json().await.map_err(TapLockError::Reqwest)?;
+
+        if !status.is_success() {
+            return Err(match body.get("error").and_then(|e| e.as_str()) {
+                Some("authorization_pending") => TapLockError::AuthorizationPending,
+                Some("slow_down") => TapLockError::SlowDown,
+                _ => TapLockError::new(format!("Device token request failed: {body}")),
+            });
+        }
+
+        let access_token = body
+            .get("id_token")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| TapLockError::new("Device token response is missing id_token"))?
+            .to_string();
+
+        let mut response = decode_token_and_maybe_refresh_jwks(self, access_token, None).await?;
+        if self.use_refresh_token {
+            response.refresh_token = body
+                .get("refresh_token")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+        }
+        Ok(response)
+    }
+}
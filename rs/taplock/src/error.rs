@@ -0,0 +1,68 @@
+use thiserror::Error;
+
+/// The crate-wide error type. Every public `taplock` function returns
+/// `Result<_, TapLockError>` so Python/R bindings only need one conversion
+/// path (`TapLockError::to_string()` for the plain case, or
+/// `extendr::device_poll_error_into_robj` where a caller needs to branch on
+/// the specific variant).
+#[derive(Debug, Error)]
+pub enum TapLockError {
+    /// One or more required environment variables are unset. Carries the
+    /// full list of missing names, rather than failing on the first one, so
+    /// a misconfigured deployment gets a single actionable error instead of
+    /// a trial-and-error loop.
+    #[error("missing required environment variable(s): {}", .0.join(", "))]
+    MissingEnv(Vec<String>),
+    /// A JWT's signature, claims, or structure failed `jsonwebtoken`
+    /// validation in a way not covered by a more specific variant below.
+    #[error("JWT error: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    /// The JWT's `kid` header is missing, or doesn't match any key in the
+    /// provider's cached JWKS.
+    #[error("no matching JWK found for the token's key ID")]
+    KidNotFound,
+    /// The JWT's signature does not verify against the selected key, or the
+    /// token is signed with an algorithm this crate does not accept.
+    #[error("token signature is invalid or uses an unaccepted algorithm")]
+    InvalidSignature,
+    /// `exp` is in the past.
+    #[error("token has expired")]
+    TokenExpired,
+    /// `nbf` is in the future.
+    #[error("token is not yet valid")]
+    TokenNotYetValid,
+    /// `iss` doesn't match the provider's expected issuer.
+    #[error("token has an invalid issuer")]
+    InvalidIssuer,
+    /// `aud` doesn't contain this client's expected audience.
+    #[error("token has an invalid audience")]
+    InvalidAudience,
+    /// The device authorization grant's `authorization_pending` error code:
+    /// the user hasn't approved the request yet. Not fatal — callers should
+    /// retry on the provider-suggested interval.
+    #[error("authorization pending: the user has not yet approved the request")]
+    AuthorizationPending,
+    /// The device authorization grant's `slow_down` error code: the caller
+    /// is polling faster than the provider's suggested interval.
+    #[error("slow down: polling interval must be increased")]
+    SlowDown,
+    /// An HTTP request to the provider failed (network error, non-2xx with
+    /// no parseable body, etc.).
+    #[error("request to the identity provider failed: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Reading or writing a file (e.g. a service-account key file) failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Anything else, with a message describing what went wrong.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl TapLockError {
+    /// Builds an `Other` error from any displayable message. The catch-all
+    /// constructor for call sites that don't map onto a more specific
+    /// variant above.
+    pub fn new(message: impl Into<String>) -> Self {
+        TapLockError::Other(message.into())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::TapLockError;
 
-use crate::OAuth2Response;
+use crate::{DeviceAuthorizationResponse, OAuth2Response};
 use extendr_api::prelude::*;
 
 fn from_json_value_to_robj(value: &serde_json::Value) -> Robj {
@@ -43,6 +43,20 @@ impl IntoRobj for &OAuth2Response {
     }
 }
 
+impl IntoRobj for &DeviceAuthorizationResponse {
+    fn into_robj(self) -> Robj {
+        list!(
+            device_code = self.device_code.clone(),
+            user_code = self.user_code.clone(),
+            verification_uri = self.verification_uri.clone(),
+            verification_uri_complete = self.verification_uri_complete.clone(),
+            expires_in = self.expires_in as f64,
+            interval = self.interval as f64
+        )
+        .into()
+    }
+}
+
 impl From<TapLockError> for extendr_api::Error {
     fn from(item: TapLockError) -> extendr_api::Error {
         extendr_api::Error::Other(item.to_string())
@@ -51,6 +65,24 @@ impl From<TapLockError> for extendr_api::Error {
 
 impl IntoRobj for TapLockError {
     fn into_robj(self) -> extendr_api::Robj {
-        extendr_api::Strings::from(self.to_string()).into_robj()
+        self.to_string().into_robj()
     }
 }
+
+/// Error conversion for the device-code poll loop only (used solely by
+/// `AsyncDeviceTokenFuture`). `authorization_pending`/`slow_down` are
+/// surfaced as flags, rather than forcing the R poll loop to match on
+/// `message`, so it can retry on `interval` seconds, back off further on a
+/// `slow_down`, and treat anything else as fatal. Every other async error
+/// path (login, refresh, revoke, service-account token) keeps going through
+/// the plain `TapLockError -> Robj` conversion above.
+pub fn device_poll_error_into_robj(err: TapLockError) -> extendr_api::Robj {
+    let authorization_pending = matches!(err, TapLockError::AuthorizationPending);
+    let slow_down = matches!(err, TapLockError::SlowDown);
+    list!(
+        message = err.to_string(),
+        authorization_pending = authorization_pending,
+        slow_down = slow_down
+    )
+    .into()
+}
@@ -2,8 +2,8 @@ pub mod auth;
 pub mod error;
 
 pub use auth::{
-    keycloak, OAuth2Client, OAuth2Response, ACCESS_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME,
-    TAPLOCK_CALLBACK_ENDPOINT,
+    keycloak, DeviceAuthorizationResponse, OAuth2Client, OAuth2Response, PkceVerifier,
+    TokenTypeHint, ACCESS_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, TAPLOCK_CALLBACK_ENDPOINT,
 };
 pub use error::TapLockError;
 